@@ -1,23 +1,146 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use indicatif::ProgressBar;
-use std::{fs, iter::zip};
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, ChangeTag, TextDiff};
+use std::{fs, iter::zip, path::Path, path::PathBuf};
 use strsim::jaro;
 
 /// CLI input arguments.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// File to process.
-    #[arg(short, long)]
-    file: std::path::PathBuf,
+    /// File(s) to process. Give a single file to scan it for internal duplication, or
+    /// two or more files to find regions duplicated between them.
+    #[arg(short, long, required = true)]
+    file: Vec<PathBuf>,
     /// Similarity threshold in [0, 1]. Only collections of lines that are more similar
     /// than this will be considered.
     #[arg(short, long, default_value_t = 0.9)]
     thres: f64,
+    /// Granularity at which lines are compared: whole trimmed lines, or word tokens
+    /// (robust to reformatting such as re-split arguments or changed indentation).
+    #[arg(short, long, value_enum, default_value_t = Granularity::Line)]
+    granularity: Granularity,
+    /// Regular expression; lines matching it (e.g. blank lines, comment-only lines,
+    /// import statements) are excluded from seeding clone matches, so boilerplate
+    /// doesn't inflate matches. They are still printed as part of any match they fall
+    /// inside.
+    #[arg(long)]
+    ignore: Option<String>,
+    /// `REGEX=REPLACEMENT`; matched spans are rewritten before comparison (but not in
+    /// the printed output), so that structurally identical but renamed code (e.g. with
+    /// collapsed identifiers or numeric literals) is still detected as duplicated.
+    #[arg(long)]
+    normalize: Option<String>,
+    /// Output format: human-readable text, or a JSON array of clone records for
+    /// tooling (editors, CI scripts) to consume.
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    /// Minimum number of lines a match must span to be reported.
+    #[arg(long, default_value_t = 5)]
+    min_lines: usize,
 }
 
-/// Line range with a beginning (inclusive) and end (inclusive).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Output format for reported clones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Plain text, with matches separated by `------` markers.
+    Human,
+    /// A JSON array of [`CloneRecord`] values.
+    Json,
+}
+
+/// Parse a `--normalize REGEX=REPLACEMENT` spec into its regex and replacement.
+fn parse_normalize(spec: &str) -> (Regex, String) {
+    let (pattern, replacement) = spec
+        .split_once('=')
+        .unwrap_or_else(|| panic!("Invalid --normalize spec '{spec}', expected REGEX=REPLACEMENT"));
+    let re = Regex::new(pattern)
+        .unwrap_or_else(|err| panic!("Invalid --normalize regex '{pattern}': {err}"));
+    (re, replacement.to_string())
+}
+
+/// Granularity at which two lines are compared for similarity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Granularity {
+    /// Compare whole trimmed lines with `jaro`.
+    Line,
+    /// Split lines into word tokens and compare the token sequences.
+    Token,
+}
+
+/// Classify a character as belonging to a word (any alphanumeric, or underscore) or
+/// not. Operates per `char` rather than per byte, so multi-byte UTF-8 codepoints are
+/// never split.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Split `line` into word tokens, where each run of word characters is one token and
+/// every other character is its own single-character token.
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if is_word_char(c) {
+            while let Some(&(_, next)) = chars.peek() {
+                if !is_word_char(next) {
+                    break;
+                }
+                end += next.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(&line[start..end]);
+    }
+    tokens
+}
+
+/// Length of the longest common subsequence of `tokens1` and `tokens2`.
+fn lcs_len(tokens1: &[&str], tokens2: &[&str]) -> usize {
+    let mut dp = vec![0usize; tokens2.len() + 1];
+    for token1 in tokens1 {
+        let mut prev_diag = 0;
+        for (j, token2) in tokens2.iter().enumerate() {
+            let prev = dp[j + 1];
+            dp[j + 1] = if token1 == token2 {
+                prev_diag + 1
+            } else {
+                dp[j + 1].max(dp[j])
+            };
+            prev_diag = prev;
+        }
+    }
+    dp[tokens2.len()]
+}
+
+/// Similarity ratio between the token sequences of `line1` and `line2`: twice the
+/// length of their longest common subsequence, divided by the total number of tokens.
+/// This mirrors the usual difflib-style ratio, but over tokens instead of characters.
+fn token_similarity(line1: &str, line2: &str) -> f64 {
+    let tokens1 = tokenize(line1);
+    let tokens2 = tokenize(line2);
+    let total = tokens1.len() + tokens2.len();
+    if total == 0 {
+        return 1.0;
+    }
+    (2 * lcs_len(&tokens1, &tokens2)) as f64 / total as f64
+}
+
+/// Similarity between two raw lines, trimmed and compared at the given `granularity`.
+fn line_similarity(line1: &str, line2: &str, granularity: Granularity) -> f64 {
+    let (line1, line2) = (line1.trim(), line2.trim());
+    match granularity {
+        Granularity::Line => jaro(line1, line2),
+        Granularity::Token => token_similarity(line1, line2),
+    }
+}
+
+/// Line range with a beginning (inclusive) and end (inclusive). Serializes as
+/// `{"start": .., "end": ..}` for `--format json` output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 struct LineRange {
     /// Start of the range (inclusive).
     start: usize,
@@ -25,21 +148,45 @@ struct LineRange {
     end: usize,
 }
 
-/// Test how similar two ranges of lines are.
+/// Average per-line similarity between `range1` (indexing `lines1`) and `range2`
+/// (indexing `lines2`), at the given `granularity`.
 ///
-/// Returns `true` if the given ranges `range1` and `range2` are more similar than the
-/// given threshold `thres`.
-fn test_ranges(range1: &LineRange, range2: &LineRange, raw_lines: &[&str], thres: f64) -> bool {
+/// `range1` indexes into `lines1` and `range2` indexes into `lines2`; for the
+/// single-file mode these are the same slice, while for cross-file comparisons they
+/// come from different files.
+fn average_similarity(
+    range1: &LineRange,
+    range2: &LineRange,
+    lines1: &[&str],
+    lines2: &[&str],
+    granularity: Granularity,
+) -> f64 {
     let n = range1.end - range1.start + 1;
     if (range2.end - range2.start + 1) != n {
         panic!("Invalid ranges {range1:?} and {range2:?}.");
     }
-    let lines1 = &raw_lines[range1.start..=range1.end];
-    let lines2 = &raw_lines[range2.start..=range2.end];
+    let slice1 = &lines1[range1.start..=range1.end];
+    let slice2 = &lines2[range2.start..=range2.end];
 
     let mut sim_sum: f64 = 0.0;
-    zip(lines1, lines2).for_each(|(line1, line2)| sim_sum += jaro(line1.trim(), line2.trim()));
-    (sim_sum / (n as f64)) > thres
+    zip(slice1, slice2)
+        .for_each(|(line1, line2)| sim_sum += line_similarity(line1, line2, granularity));
+    sim_sum / (n as f64)
+}
+
+/// Test how similar two ranges of lines are.
+///
+/// Returns `true` if the given ranges `range1` and `range2` are more similar than the
+/// given threshold `thres`.
+fn test_ranges(
+    range1: &LineRange,
+    range2: &LineRange,
+    lines1: &[&str],
+    lines2: &[&str],
+    thres: f64,
+    granularity: Granularity,
+) -> bool {
+    average_similarity(range1, range2, lines1, lines2, granularity) > thres
 }
 
 /// Line range expansion position.
@@ -71,23 +218,34 @@ fn add_row(range: &LineRange, position: &Position) -> LineRange {
 /// Otherwise, returns `Some(false, out_range1, out_range2)`.
 /// If the ranges could be grown successfully but this was already recorded previously
 /// in `visited`, `None` is returned.
+#[allow(clippy::too_many_arguments)]
 fn grow_at_position(
     range1: LineRange,
     range2: LineRange,
-    raw_lines: &[&str],
+    lines1: &[&str],
+    lines2: &[&str],
     thres: f64,
     visited: &mut Vec<(LineRange, LineRange)>,
     position: &Position,
-    n_lines: usize,
+    n_lines1: usize,
+    n_lines2: usize,
+    granularity: Granularity,
 ) -> Option<(bool, LineRange, LineRange)> {
     let condition = match position {
         Position::Start => (range1.start >= 1) && (range2.start >= 1),
-        Position::End => (range1.end < (n_lines - 1)) && (range2.end < (n_lines - 1)),
+        Position::End => (range1.end < (n_lines1 - 1)) && (range2.end < (n_lines2 - 1)),
     };
     if condition {
         let trial_range1 = add_row(&range1, position);
         let trial_range2 = add_row(&range2, position);
-        if test_ranges(&trial_range1, &trial_range2, raw_lines, thres) {
+        if test_ranges(
+            &trial_range1,
+            &trial_range2,
+            lines1,
+            lines2,
+            thres,
+            granularity,
+        ) {
             if visited.contains(&(trial_range1, trial_range2)) {
                 return None;
             }
@@ -101,20 +259,32 @@ fn grow_at_position(
 /// Starting from `range1` and `range2`, assimilate similar surrounding lines until they
 /// are no longer similar enough (as measured by `thres`).
 ///
+/// `lines1` and `lines2` are the same slice when growing ranges within a single file,
+/// and distinct slices when growing a match found between two files. `guard_adjacency`
+/// should be `true` in the former case, to avoid growing a range into a trivially
+/// adjacent copy of itself; it is meaningless across files and should be `false` there.
+///
 /// `visited` and `leaves` are modified in-place to record the matching sets of lines,
 /// where `leaves` will contain only the subset of `visited` that consists of the
 /// largest intersections without any intermediaries.
+#[allow(clippy::too_many_arguments)]
 fn grow_ranges(
     mut range1: LineRange,
     mut range2: LineRange,
-    raw_lines: &Vec<&str>,
+    lines1: &[&str],
+    lines2: &[&str],
     thres: f64,
     visited: &mut Vec<(LineRange, LineRange)>,
     leaves: &mut Vec<(LineRange, LineRange)>,
+    guard_adjacency: bool,
+    granularity: Granularity,
 ) {
-    let n_lines = raw_lines.len();
+    let n_lines1 = lines1.len();
+    let n_lines2 = lines2.len();
 
-    if (range1.end.abs_diff(range2.start) == 1) || (range1.start.abs_diff(range2.end) == 1) {
+    if guard_adjacency
+        && ((range1.end.abs_diff(range2.start) == 1) || (range1.start.abs_diff(range2.end) == 1))
+    {
         return;
     }
 
@@ -122,7 +292,16 @@ fn grow_ranges(
         let mut grew_both = true;
         for position in [Position::Start, Position::End] {
             if let Some((grew, out_range1, out_range2)) = grow_at_position(
-                range1, range2, raw_lines, thres, visited, &position, n_lines,
+                range1,
+                range2,
+                lines1,
+                lines2,
+                thres,
+                visited,
+                &position,
+                n_lines1,
+                n_lines2,
+                granularity,
             ) {
                 if !grew {
                     grew_both = false;
@@ -141,39 +320,177 @@ fn grow_ranges(
     leaves.push((range1, range2));
 }
 
-/// Find similar lines within a given file.
-fn main() {
-    let args = Args::parse();
-    let thres = args.thres;
+/// Read the contents of `path`, panicking with a helpful message if that fails.
+fn read_file(path: &Path) -> String {
+    let Ok(file_path) = path.canonicalize() else {
+        panic!("Invalid file '{path:?}'")
+    };
+    fs::read_to_string(file_path).expect("Unable to read file.")
+}
+
+/// Build the view of `raw_lines` that comparisons are run against: each line has
+/// `normalize`'s matched spans rewritten, and is flagged in the returned mask if it
+/// matches `ignore` and so must not be used to seed a clone match. `raw_lines` itself
+/// is left untouched, so it can still be used for printing the original text.
+fn build_compare_lines(
+    raw_lines: &[&str],
+    ignore: Option<&Regex>,
+    normalize: Option<&(Regex, String)>,
+) -> (Vec<String>, Vec<bool>) {
+    raw_lines
+        .iter()
+        .map(|line| {
+            let is_ignored = ignore.is_some_and(|re| re.is_match(line.as_bytes()));
+            let normalized = match normalize {
+                Some((re, replacement)) => String::from_utf8(
+                    re.replace_all(line.as_bytes(), replacement.as_bytes())
+                        .into_owned(),
+                )
+                .expect("--normalize replacement produced invalid UTF-8"),
+                None => (*line).to_string(),
+            };
+            (normalized, is_ignored)
+        })
+        .unzip()
+}
+
+/// Whether every line of `range` is flagged as ignored in `ignored`.
+fn is_fully_ignored(range: &LineRange, ignored: &[bool]) -> bool {
+    ignored[range.start..=range.end]
+        .iter()
+        .all(|&is_ignored| is_ignored)
+}
+
+/// A single detected clone: the matching ranges, their original text, the computed
+/// similarity, and (for cross-file matches) which file each range came from.
+#[derive(Debug, Serialize)]
+struct CloneRecord {
+    a: LineRange,
+    b: LineRange,
+    text_a: String,
+    text_b: String,
+    similarity: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_a: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_b: Option<String>,
+}
+
+/// Print `records` in the given `format`.
+fn report(records: &[CloneRecord], format: Format) {
+    match format {
+        Format::Human => records.iter().for_each(|record| {
+            if let Some(file_a) = &record.file_a {
+                println!("{file_a}:");
+            }
+            println!("{}", record.text_a);
+            println!("------");
+            if let Some(file_b) = &record.file_b {
+                println!("{file_b}:");
+            }
+            println!("{}", record.text_b);
+            println!("------");
+            println!("------");
+        }),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(records).expect("Unable to serialize clone records.")
+        ),
+    }
+}
+
+/// Find every maximal run of unchanged lines between `contents1` and `contents2`,
+/// using a Patience diff so that reordered or inserted blocks don't fragment runs that
+/// Myers' algorithm would otherwise split.
+///
+/// Each run is returned as a `(LineRange, LineRange)` anchor, indexing into
+/// `contents1` and `contents2` respectively, suitable for seeding [`grow_ranges`].
+fn seed_equal_ranges(contents1: &str, contents2: &str) -> Vec<(LineRange, LineRange)> {
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Patience)
+        .diff_lines(contents1, contents2);
+
+    let mut anchors = Vec::new();
+    let mut run: Option<(usize, usize, usize)> = None;
 
+    for change in diff.iter_all_changes() {
+        if change.tag() == ChangeTag::Equal {
+            let old_index = change.old_index().expect("equal change has an old index");
+            let new_index = change.new_index().expect("equal change has a new index");
+            run = match run {
+                Some((start1, start2, len))
+                    if old_index == start1 + len && new_index == start2 + len =>
+                {
+                    Some((start1, start2, len + 1))
+                }
+                _ => Some((old_index, new_index, 1)),
+            };
+        } else if let Some((start1, start2, len)) = run.take() {
+            anchors.push((
+                LineRange {
+                    start: start1,
+                    end: start1 + len - 1,
+                },
+                LineRange {
+                    start: start2,
+                    end: start2 + len - 1,
+                },
+            ));
+        }
+    }
+    if let Some((start1, start2, len)) = run {
+        anchors.push((
+            LineRange {
+                start: start1,
+                end: start1 + len - 1,
+            },
+            LineRange {
+                start: start2,
+                end: start2 + len - 1,
+            },
+        ));
+    }
+    anchors
+}
+
+/// Find similar lines within a single file, via the O(n^2) pairwise seed scan.
+#[allow(clippy::too_many_arguments)]
+fn run_single_file(
+    path: &Path,
+    thres: f64,
+    granularity: Granularity,
+    ignore: Option<&Regex>,
+    normalize: Option<&(Regex, String)>,
+    format: Format,
+    min_lines: usize,
+) {
     let mut visited: Vec<(LineRange, LineRange)> = Vec::new();
     let mut leaves: Vec<(LineRange, LineRange)> = Vec::new();
 
-    let Ok(file_path) = args.file.canonicalize() else {
-        panic!("Invalid file '{:?}'", args.file)
-    };
-
-    let contents = fs::read_to_string(file_path).expect("Unable to read file.");
+    let contents = read_file(path);
 
     let lines_iter = contents.lines();
     let raw_lines: &Vec<&str> = &lines_iter.clone().collect();
+    let (compare_lines, ignored) = build_compare_lines(raw_lines, ignore, normalize);
+    let compare_lines: &Vec<&str> = &compare_lines.iter().map(String::as_str).collect();
 
     let n = raw_lines.len() - 1;
     let bar = ProgressBar::new((n * (n + 1) / 2).try_into().unwrap());
-    for (i, line1) in lines_iter.clone().enumerate() {
-        for (j_i, line2) in lines_iter.clone().skip(i + 1).enumerate() {
-            // Correct for the offset applied using `skip`.
-            let j = j_i + i + 1;
-            // Trim lines to enable comparison.
-            let trimmed_lines = [line1, line2].map(|s| s.trim());
-            if jaro(trimmed_lines[0], trimmed_lines[1]) > thres {
+    for i in 0..raw_lines.len() {
+        for j in (i + 1)..raw_lines.len() {
+            if !(ignored[i] || ignored[j])
+                && line_similarity(compare_lines[i], compare_lines[j], granularity) > thres
+            {
                 grow_ranges(
                     LineRange { start: i, end: i },
                     LineRange { start: j, end: j },
-                    raw_lines,
+                    compare_lines,
+                    compare_lines,
                     thres,
                     &mut visited,
                     &mut leaves,
+                    true,
+                    granularity,
                 );
             }
             bar.inc(1);
@@ -181,15 +498,138 @@ fn main() {
     }
     bar.finish();
 
-    leaves.into_iter().for_each(|(lines1, lines2)| {
-        if (lines2.end - lines2.start) >= 5 {
-            println!("{}", raw_lines[lines1.start..=lines1.end].join("\n"));
-            println!("------");
-            println!("{}", raw_lines[lines2.start..=lines2.end].join("\n"));
-            println!("------");
-            println!("------");
+    let records: Vec<CloneRecord> = leaves
+        .into_iter()
+        .filter(|(_, range2)| (range2.end - range2.start + 1) >= min_lines)
+        .map(|(range1, range2)| CloneRecord {
+            text_a: raw_lines[range1.start..=range1.end].join("\n"),
+            text_b: raw_lines[range2.start..=range2.end].join("\n"),
+            similarity: average_similarity(
+                &range1,
+                &range2,
+                compare_lines,
+                compare_lines,
+                granularity,
+            ),
+            a: range1,
+            b: range2,
+            file_a: None,
+            file_b: None,
+        })
+        .collect();
+    report(&records, format);
+}
+
+/// Find regions duplicated between every pair of the given files, seeding the fuzzy
+/// [`grow_ranges`] expansion from the equal runs of a Patience line diff rather than
+/// from an O(n^2) scan.
+#[allow(clippy::too_many_arguments)]
+fn run_cross_file(
+    paths: &[PathBuf],
+    thres: f64,
+    granularity: Granularity,
+    ignore: Option<&Regex>,
+    normalize: Option<&(Regex, String)>,
+    format: Format,
+    min_lines: usize,
+) {
+    let contents: Vec<String> = paths.iter().map(|path| read_file(path)).collect();
+    let raw_lines: Vec<Vec<&str>> = contents.iter().map(|c| c.lines().collect()).collect();
+    let compare: Vec<(Vec<String>, Vec<bool>)> = raw_lines
+        .iter()
+        .map(|lines| build_compare_lines(lines, ignore, normalize))
+        .collect();
+    let compare_lines: Vec<Vec<&str>> = compare
+        .iter()
+        .map(|(lines, _)| lines.iter().map(String::as_str).collect())
+        .collect();
+    let compare_contents: Vec<String> =
+        compare_lines.iter().map(|lines| lines.join("\n")).collect();
+
+    let mut records: Vec<CloneRecord> = Vec::new();
+    for i in 0..paths.len() {
+        for j in (i + 1)..paths.len() {
+            let lines1 = &raw_lines[i];
+            let lines2 = &raw_lines[j];
+            let (compare1, ignored1) = (&compare_lines[i], &compare[i].1);
+            let (compare2, ignored2) = (&compare_lines[j], &compare[j].1);
+
+            let mut visited: Vec<(LineRange, LineRange)> = Vec::new();
+            let mut leaves: Vec<(LineRange, LineRange)> = Vec::new();
+
+            for (anchor1, anchor2) in seed_equal_ranges(&compare_contents[i], &compare_contents[j])
+            {
+                if is_fully_ignored(&anchor1, ignored1) || is_fully_ignored(&anchor2, ignored2) {
+                    continue;
+                }
+                grow_ranges(
+                    anchor1,
+                    anchor2,
+                    compare1,
+                    compare2,
+                    thres,
+                    &mut visited,
+                    &mut leaves,
+                    false,
+                    granularity,
+                );
+            }
+
+            records.extend(
+                leaves
+                    .into_iter()
+                    .filter(|(_, range2)| (range2.end - range2.start + 1) >= min_lines)
+                    .map(|(range1, range2)| CloneRecord {
+                        text_a: lines1[range1.start..=range1.end].join("\n"),
+                        text_b: lines2[range2.start..=range2.end].join("\n"),
+                        similarity: average_similarity(
+                            &range1,
+                            &range2,
+                            compare1,
+                            compare2,
+                            granularity,
+                        ),
+                        a: range1,
+                        b: range2,
+                        file_a: Some(paths[i].display().to_string()),
+                        file_b: Some(paths[j].display().to_string()),
+                    }),
+            );
         }
-    })
+    }
+    report(&records, format);
+}
+
+/// Find similar lines within a file, or duplicated regions between files.
+fn main() {
+    let args = Args::parse();
+
+    let ignore = args.ignore.as_deref().map(|pattern| {
+        Regex::new(pattern)
+            .unwrap_or_else(|err| panic!("Invalid --ignore regex '{pattern}': {err}"))
+    });
+    let normalize = args.normalize.as_deref().map(parse_normalize);
+
+    match args.file.as_slice() {
+        [path] => run_single_file(
+            path,
+            args.thres,
+            args.granularity,
+            ignore.as_ref(),
+            normalize.as_ref(),
+            args.format,
+            args.min_lines,
+        ),
+        paths => run_cross_file(
+            paths,
+            args.thres,
+            args.granularity,
+            ignore.as_ref(),
+            normalize.as_ref(),
+            args.format,
+            args.min_lines,
+        ),
+    }
 }
 
 #[cfg(test)]
@@ -198,14 +638,41 @@ mod tests {
 
     #[test]
     fn testing_similar_ranges() {
+        let raw_lines = vec!["abc", "def", "abc", "deg"];
         assert!(test_ranges(
             &LineRange { start: 0, end: 1 },
             &LineRange { start: 2, end: 3 },
-            &vec!["abc", "def", "abc", "deg"],
+            &raw_lines,
+            &raw_lines,
             0.8,
+            Granularity::Line,
         ));
     }
 
+    #[test]
+    fn tokenizes_on_word_boundaries() {
+        assert_eq!(
+            tokenize("foo(bar, baz)"),
+            vec!["foo", "(", "bar", ",", " ", "baz", ")"]
+        );
+    }
+
+    #[test]
+    fn tokenizes_multi_byte_characters_without_splitting_them() {
+        assert_eq!(
+            tokenize("café \"smart quotes\""),
+            vec!["café", " ", "\"", "smart", " ", "quotes", "\""]
+        );
+    }
+
+    #[test]
+    fn token_similarity_survives_reformatting() {
+        assert_eq!(token_similarity("foo(bar, baz)", "foo(bar, baz)"), 1.0);
+        // Dropping a space still scores highly relative to changing an identifier.
+        assert!(token_similarity("foo(bar, baz)", "foo(bar,baz)") > 0.9);
+        assert!(token_similarity("foo(bar, baz)", "foo(quux, baz)") < 0.9);
+    }
+
     #[test]
     fn row_addition() {
         assert_eq!(
@@ -220,16 +687,20 @@ mod tests {
 
     #[test]
     fn grow_line_ranges() {
+        let raw_lines = vec!["abc", "def", "abc", "deg"];
         let mut visited: Vec<(LineRange, LineRange)> = Vec::new();
 
         grow_at_position(
             LineRange { start: 0, end: 0 },
             LineRange { start: 2, end: 2 },
-            &vec!["abc", "def", "abc", "deg"],
+            &raw_lines,
+            &raw_lines,
             0.8,
             &mut visited,
             &Position::End,
             4,
+            4,
+            Granularity::Line,
         );
         assert_eq!(
             visited,
@@ -245,10 +716,13 @@ mod tests {
         grow_ranges(
             LineRange { start: 0, end: 0 },
             LineRange { start: 2, end: 2 },
-            &vec!["abc", "def", "abc", "deg"],
+            &raw_lines,
+            &raw_lines,
             0.8,
             &mut visited,
             &mut leaves,
+            true,
+            Granularity::Line,
         );
         assert_eq!(
             visited,
@@ -265,4 +739,53 @@ mod tests {
             )]
         );
     }
+
+    #[test]
+    fn seeds_equal_runs_between_files() {
+        let contents1 = "a\nb\nc\nd\ne\n";
+        let contents2 = "x\nb\nc\nd\ny\n";
+        let anchors = seed_equal_ranges(contents1, contents2);
+        assert_eq!(
+            anchors,
+            vec![(
+                LineRange { start: 1, end: 3 },
+                LineRange { start: 1, end: 3 }
+            )]
+        );
+    }
+
+    #[test]
+    fn ignore_flags_matching_lines() {
+        let raw_lines = vec!["use std::fs;", "let x = 1;", ""];
+        let ignore = Regex::new(r"^\s*(use |$)").unwrap();
+        let (compare_lines, ignored) = build_compare_lines(&raw_lines, Some(&ignore), None);
+        assert_eq!(ignored, vec![true, false, true]);
+        assert_eq!(compare_lines, raw_lines);
+    }
+
+    #[test]
+    fn normalize_rewrites_matched_spans() {
+        let raw_lines = vec!["let x = 1;", "let y = 2;"];
+        let normalize = parse_normalize(r"\d+=N");
+        let (compare_lines, _) = build_compare_lines(&raw_lines, None, Some(&normalize));
+        assert_eq!(compare_lines, vec!["let x = N;", "let y = N;"]);
+    }
+
+    #[test]
+    fn clone_record_serializes_to_the_documented_shape() {
+        let record = CloneRecord {
+            a: LineRange { start: 0, end: 1 },
+            b: LineRange { start: 4, end: 5 },
+            text_a: "abc\ndef".to_string(),
+            text_b: "abc\ndef".to_string(),
+            similarity: 1.0,
+            file_a: None,
+            file_b: None,
+        };
+        let value: serde_json::Value = serde_json::to_value(&record).unwrap();
+        assert_eq!(value["a"], serde_json::json!({"start": 0, "end": 1}));
+        assert_eq!(value["b"], serde_json::json!({"start": 4, "end": 5}));
+        assert_eq!(value["similarity"], 1.0);
+        assert!(value.get("file_a").is_none());
+    }
 }